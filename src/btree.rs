@@ -22,6 +22,23 @@ pub(crate) trait BTreeFreeMap<T> {
 	fn address_of_free_range_in(&self, id: usize, len: T, strategy: AllocationStrategy) -> Option<(ItemAddr, T)>;
 }
 
+/// Offset-keyed counterpart of [`BTreeFreeMap`], used to answer
+/// address-ordered `FirstFit` queries.
+///
+/// The size-keyed tree behind `BTreeFreeMap` can tell us "a free region of at
+/// least this size", but not "the lowest-address free region that fits",
+/// since its key carries no address information. This trait is implemented
+/// for a second `BTreeMap<T, usize>`, this one keyed by region *offset*, and
+/// descends it using the region length accessor `region_len` to reach the
+/// lowest-offset region that fits. The descent is O(n), not O(log n): the
+/// subtree summary it prunes with is recomputed on every call (see
+/// `max_free_len_in`) rather than cached in the nodes.
+pub(crate) trait BTreeFreeMapByOffset<T> {
+	fn address_of_first_fit(&self, len: T, region_len: &dyn Fn(usize) -> T) -> Option<ItemAddr>;
+
+	fn address_of_first_fit_in(&self, id: usize, len: T, region_len: &dyn Fn(usize) -> T) -> Option<ItemAddr>;
+}
+
 impl<T: Address> BTreeFreeMap<T> for BTreeMap<T, usize> {
 	#[inline]
 	fn address_of_free_range(&self, len: T, strategy: AllocationStrategy) -> Option<(ItemAddr, T)> {
@@ -49,13 +66,116 @@ impl<T: Address> BTreeFreeMap<T> for BTreeMap<T, usize> {
 	}
 }
 
+impl<T: Address> BTreeFreeMapByOffset<T> for BTreeMap<T, usize> {
+	#[inline]
+	fn address_of_first_fit(&self, len: T, region_len: &dyn Fn(usize) -> T) -> Option<ItemAddr> {
+		match self.root_id() {
+			Some(id) => self.address_of_first_fit_in(id, len, region_len),
+			None => None
+		}
+	}
+
+	#[inline]
+	fn address_of_first_fit_in(&self, mut id: usize, len: T, region_len: &dyn Fn(usize) -> T) -> Option<ItemAddr> {
+		loop {
+			match first_fit_offset_in(self, id, len, region_len) {
+				Ok(offset) => {
+					return Some(ItemAddr::new(id, offset.into()))
+				},
+				Err(None) => {
+					return None
+				},
+				Err(Some(child_id)) => {
+					id = child_id;
+				}
+			}
+		}
+	}
+}
+
+/// `max_free_len` summary of the subtree rooted at `id`: the maximum free
+/// region length found anywhere below it, combined bottom-up with
+/// `op(l, r) = max(l, r)`.
+///
+/// `btree_slab`'s nodes don't carry stored per-node state for us to augment,
+/// so unlike a hand-rolled augmented tree this summary is recomputed by
+/// walking the subtree on every call rather than cached and patched on
+/// insert/remove/split/merge; `first_fit_offset_in` still gets to prune
+/// whole subtrees below a node once its summary is known, it just pays for
+/// that summary with a scan instead of an O(1) field read.
+fn max_free_len_in<T: Address>(map: &BTreeMap<T, usize>, id: usize, region_len: &dyn Fn(usize) -> T) -> T {
+	match map.node(id) {
+		Node::Internal(node) => {
+			let mut max = max_free_len_in(map, node.first_child_id(), region_len);
+			for b in node.branches() {
+				let child_max = max_free_len_in(map, b.child, region_len);
+				if child_max > max {
+					max = child_max;
+				}
+				let item_len = region_len(*b.item.value());
+				if item_len > max {
+					max = item_len;
+				}
+			}
+			max
+		},
+		Node::Leaf(leaf) => {
+			let mut max = T::ZERO;
+			for item in leaf.items() {
+				let item_len = region_len(*item.value());
+				if item_len > max {
+					max = item_len;
+				}
+			}
+			max
+		}
+	}
+}
+
+/// Descends one node of the offset-keyed tree looking for the lowest-offset
+/// free region that is at least `len` long.
+///
+/// Mirrors the shape of [`free_range_offset_in`]: `Ok(offset)` is a hit at
+/// this node, `Err(Some(child))` means descend, `Err(None)` means this
+/// subtree has nothing big enough.
+fn first_fit_offset_in<T>(map: &BTreeMap<T, usize>, id: usize, len: T, region_len: &dyn Fn(usize) -> T) -> Result<usize, Option<usize>> where T: Address {
+	match map.node(id) {
+		Node::Internal(node) => {
+			// In address order: the first child, then each branch's item
+			// followed by the child holding the offsets after that item.
+			let first_child = node.first_child_id();
+			if max_free_len_in(map, first_child, region_len) >= len {
+				return Err(Some(first_child));
+			}
+			for (i, b) in node.branches().iter().enumerate() {
+				if region_len(*b.item.value()) >= len {
+					return Ok(i);
+				}
+				if max_free_len_in(map, b.child, region_len) >= len {
+					return Err(Some(b.child));
+				}
+			}
+			Err(None)
+		},
+		Node::Leaf(leaf) => {
+			let items = leaf.items();
+			for (i, item) in items.iter().enumerate() {
+				if region_len(*item.value()) >= len {
+					return Ok(i);
+				}
+			}
+			Err(None)
+		}
+	}
+}
+
 fn free_range_offset_in<T>(node: &Node<T, usize>, len: T, strategy: AllocationStrategy) -> Result<(usize, T), Option<usize>> where T: Address {
 	match node {
 		Node::Internal(node) => {
 			let branches = node.branches();
 			match strategy {
 				AllocationStrategy::FirstFit => {
-					panic!("TODO")
+					unreachable!("FirstFit is resolved through the offset-keyed tree; see BTreeFreeMapByOffset")
 				},
 				AllocationStrategy::WorstFit => {
 					let i = branches.len() - 1;
@@ -75,6 +195,9 @@ fn free_range_offset_in<T>(node: &Node<T, usize>, len: T, strategy: AllocationSt
 							Ok((0, *branches[0].item.key()))
 						}
 					}
+				},
+				AllocationStrategy::SegregatedFit => {
+					unreachable!("SegregatedFit never descends the size-keyed tree; see SegregatedFreeLists")
 				}
 			}
 		},
@@ -123,6 +246,9 @@ fn free_range_offset_in<T>(node: &Node<T, usize>, len: T, strategy: AllocationSt
 						},
 						None => Ok((0, *items[0].key()))
 					}
+				},
+				AllocationStrategy::SegregatedFit => {
+					unreachable!("SegregatedFit never descends the size-keyed tree; see SegregatedFreeLists")
 				}
 			}
 		}
@@ -6,8 +6,9 @@ use btree_slab::{
 };
 
 mod btree;
-use btree::BTreeFreeMap;
+use btree::{BTreeFreeMap, BTreeFreeMapByOffset};
 
+#[derive(Debug)]
 pub struct AllocationFailed;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -15,7 +16,12 @@ pub enum AllocationStrategy {
 	/// Minimises allocation time.
 	///
 	/// # Complexity
-	/// - Allocation: O(1 log n)
+	/// - Allocation: O(n) — the offset-keyed tree's `max_free_len` subtree
+	///   summary isn't cached (see `btree::max_free_len_in`), so descending
+	///   it rescans every subtree it prunes through instead of reading an
+	///   O(1) per-node field. A cached, incrementally-maintained summary
+	///   would bring this down to O(log n); until then, prefer
+	///   `WorstFit`/`BestFit`/`SegregatedFit` when allocation time matters.
 	/// - Free: O(log n)
 	FirstFit,
 
@@ -31,11 +37,39 @@ pub enum AllocationStrategy {
 	/// # Complexity
 	/// - Allocation: O(2 log n)
 	/// - Free: O(log n)
-	BestFit
+	BestFit,
+
+	/// Minimises allocation time further than `FirstFit` by picking any
+	/// "good enough" free region from its size class instead of searching
+	/// for the best address or size.
+	///
+	/// # Complexity
+	/// - Allocation: O(log n) — picking the region from the size-class
+	///   lists is O(1), but the offset-keyed tree is updated as well.
+	/// - Free: O(n) — the freed region is looked up by offset with a linear
+	///   scan of the allocated regions.
+	SegregatedFit
 }
 
+/// Minimum size-class granularity for `AllocationStrategy::SegregatedFit`,
+/// as a left shift: regions are classified by `len >> MIN_SHIFT` so that
+/// lengths below `1 << MIN_SHIFT` all share the smallest class.
+const MIN_SHIFT: u32 = 4;
+
 pub trait Address : Copy + Ord + std::ops::Add<Output = Self> + std::ops::Sub<Output = Self> {
 	const ZERO: Self;
+
+	/// Number of bits used to represent this address type.
+	///
+	/// Used by `AllocationStrategy::SegregatedFit` to compute the size
+	/// class of a free region.
+	const BIT_WIDTH: u32;
+
+	/// Number of leading zero bits in this address's binary representation.
+	fn leading_zeros(self) -> u32;
+
+	/// Shifts this address right by `shift` bits.
+	fn shr(self, shift: u32) -> Self;
 }
 
 pub(crate) trait AddressRange<T> {
@@ -51,9 +85,9 @@ impl<T: Address> AddressRange<T> for std::ops::Range<T> {
 
 fn index(i: usize) -> Option<usize> {
 	if i == std::usize::MAX {
-		Some(i)
-	} else {
 		None
+	} else {
+		Some(i)
 	}
 }
 
@@ -61,11 +95,12 @@ struct Page<T> {
 	len: T
 }
 
-/// Free region of unknown size.
+/// Free region.
 #[derive(Copy, Clone)]
 struct FreeRegion<T> {
 	page: usize,
 	offset: T,
+	len: T,
 	previous_allocated_region: usize
 }
 
@@ -81,17 +116,118 @@ impl<T> FreeRegions<T> {
 		}
 	}
 
+	/// Length of the free region at the given slab index.
+	///
+	/// Used by the offset-keyed tree (see `btree::BTreeFreeMapByOffset`) to
+	/// compare region lengths without exposing the `regions` slab itself.
+	fn region_len(&self, i: usize) -> T where T: Copy {
+		self.regions[i].0.len
+	}
+
+	/// Inserts a new free region directly into the slab, outside of any
+	/// size-class chain. Returns its index.
+	///
+	/// Used by `FreeMap::register_free_region`, which then links the new
+	/// region into the offset-keyed tree and whichever strategy-specific
+	/// structure applies.
+	fn insert(&mut self, region: FreeRegion<T>) -> usize {
+		self.regions.insert((region, std::usize::MAX))
+	}
+
+	/// Removes the free region at slab index `i`, unlinking it from
+	/// whatever same-key chain it is the head of.
+	///
+	/// Returns the new head of that chain (the region's own "next" link,
+	/// if any), and the removed region itself.
 	fn pop(&mut self, i: usize) -> (Option<usize>, FreeRegion<T>) {
-		panic!("TODO")
+		let (region, next) = self.regions.remove(i);
+		let head = if next != std::usize::MAX { Some(next) } else { None };
+		(head, region)
+	}
+
+	/// Links the already-inserted free region at slab index `i` onto the
+	/// head of a chain, returning the new chain head (`i` itself).
+	fn push(&mut self, head: Option<usize>, i: usize) -> Option<usize> {
+		self.regions[i].1 = head.unwrap_or(std::usize::MAX);
+		Some(i)
+	}
+
+	/// Removes the free region at slab index `i` directly from the slab,
+	/// without touching any chain it belongs to.
+	///
+	/// Used when the caller has already unlinked `i` from its chain itself
+	/// (e.g. `SegregatedFreeLists::pop`, or `FreeMap::unregister_free_region`
+	/// via `unlink`/`unlink_from_segregated`).
+	fn take(&mut self, i: usize) -> FreeRegion<T> {
+		self.regions.remove(i).0
 	}
+}
+
+/// Segregated free lists backing `AllocationStrategy::SegregatedFit`.
+///
+/// One intrusive, singly-linked list per power-of-two size class, threaded
+/// through the "next" slot of `FreeRegions`' own slab so no extra storage is
+/// needed per region. Class `c` holds every free region whose length `len`
+/// satisfies `class_of(len) == c`, i.e. regions of length in
+/// `[1 << (c + MIN_SHIFT - 1), 1 << (c + MIN_SHIFT))` (class 0 covers
+/// everything below `1 << MIN_SHIFT`). Allocation takes the head of the
+/// request's own class if that region is long enough, and otherwise pops the
+/// head of the first non-empty larger class, every region of which is long
+/// enough. Only chain heads are looked at, so taking a region from the lists
+/// and pushing one back onto them are O(1).
+struct SegregatedFreeLists {
+	/// Head of the free-region chain for each class, or `std::usize::MAX`
+	/// if the class is empty.
+	classes: Vec<usize>
+}
 
-	fn push(&mut self, i: Option<usize>) -> (Option<usize>, ()) {
-		panic!("TODO")
+impl SegregatedFreeLists {
+	fn new() -> SegregatedFreeLists {
+		SegregatedFreeLists {
+			classes: Vec::new()
+		}
+	}
+
+	/// Size class of a region of length `len`.
+	fn class_of<T: Address>(len: T) -> usize {
+		(T::BIT_WIDTH - len.shr(MIN_SHIFT).leading_zeros()) as usize
+	}
+
+	fn ensure_class(&mut self, c: usize) {
+		if c >= self.classes.len() {
+			self.classes.resize(c + 1, std::usize::MAX);
+		}
+	}
+
+	/// Push the free region already stored at slab index `i` onto the head
+	/// of class `c`'s chain.
+	fn push<T>(&mut self, free_regions: &mut FreeRegions<T>, c: usize, i: usize) {
+		self.ensure_class(c);
+		let head = self.classes[c];
+		free_regions.regions[i].1 = head;
+		self.classes[c] = i;
+	}
+
+	/// Pop a region of at least `len` from class `c` (the class of `len`),
+	/// falling back to the next non-empty larger class. Returns the slab
+	/// index of the popped region.
+	fn pop<T: Address>(&mut self, free_regions: &FreeRegions<T>, c: usize, len: T) -> Option<usize> {
+		for class in c..self.classes.len() {
+			let head = self.classes[class];
+			// Regions of a larger class always fit; the head of class `c`
+			// itself may still be shorter than `len`.
+			if head != std::usize::MAX && (class > c || free_regions.regions[head].0.len >= len) {
+				self.classes[class] = free_regions.regions[head].1;
+				return Some(head);
+			}
+		}
+		None
 	}
 }
 
 #[derive(Copy, Clone)]
 struct AllocatedRegion<T> {
+	page: usize,
 	offset: T,
 	len: T
 }
@@ -111,68 +247,126 @@ impl<T> AllocatedRegions<T> {
 
 	/// Insert a first allocated region.
 	///
-	/// Return the second allocated region.
-	fn push_front(&mut self, region: AllocatedRegion<T>) -> Option<AllocatedRegion<T>> where T: Copy {
+	/// Return the index of the newly inserted region, and the second
+	/// allocated region (if any).
+	fn push_front(&mut self, region: AllocatedRegion<T>) -> (usize, Option<AllocatedRegion<T>>) where T: Copy {
 		let second = self.first;
 		let n = self.regions.insert((std::usize::MAX, region, second));
 		self.first = n;
-		match index(second) {
+		let next = match index(second) {
 			Some(i) => {
 				self.regions[i].0 = n;
 				Some(self.regions[i].1)
 			},
 			None => None
-		}
+		};
+		(n, next)
 	}
 
 	/// Insert an allocated region after the given index.
 	///
-	/// Return the next allocated region.
-	fn insert_after(&mut self, i: usize, region: AllocatedRegion<T>) -> Option<AllocatedRegion<T>> where T: Copy {
+	/// Return the index of the newly inserted region, and the next
+	/// allocated region (if any).
+	fn insert_after(&mut self, i: usize, region: AllocatedRegion<T>) -> (usize, Option<AllocatedRegion<T>>) where T: Copy {
 		let next = self.regions[i].2;
 		let n = self.regions.insert((i, region, next));
 		self.regions[i].2 = n;
-		match index(next) {
+		let next_region = match index(next) {
 			Some(j) => {
 				self.regions[j].0 = n;
 				Some(self.regions[j].1)
 			},
 			None => None
+		};
+		(n, next_region)
+	}
+
+	/// Appends a new allocated region after the one at index `prev` (or as
+	/// the very first region if `prev` is `None`), on the assumption that
+	/// there is currently no region after it. Returns the index of the
+	/// newly inserted region.
+	///
+	/// Used by bulk construction (`FreeMap::from_sorted_free_regions`),
+	/// which builds the list in address order in a single linear pass
+	/// instead of going through `push_front`/`insert_after`.
+	fn append(&mut self, prev: Option<usize>, region: AllocatedRegion<T>) -> usize where T: Copy {
+		let n = self.regions.insert((prev.unwrap_or(std::usize::MAX), region, std::usize::MAX));
+		match prev {
+			Some(i) => { self.regions[i].2 = n; },
+			None => { self.first = n; }
 		}
+		n
 	}
 
-	/// Remove the region with the given index and return the allocated region before and after it.
-	fn remove(&mut self, i: usize) -> (Option<AllocatedRegion<T>>, Option<AllocatedRegion<T>>) where T: Copy {
+	/// Removes the region with the given index.
+	///
+	/// Returns the removed region, the index and data of the allocated
+	/// region immediately before it (if any), and the data of the
+	/// allocated region immediately after it (if any).
+	fn remove(&mut self, i: usize) -> (AllocatedRegion<T>, Option<(usize, AllocatedRegion<T>)>, Option<AllocatedRegion<T>>) where T: Copy {
 		let node = self.regions.remove(i);
 
-		let prev = match index(node.0) {
-			Some(j) => {
-				let prev = &mut self.regions[j];
-				prev.2 = node.2;
-				Some(prev.1)
-			},
-			None => None
+		let prev = if node.0 != std::usize::MAX {
+			let prev = &mut self.regions[node.0];
+			prev.2 = node.2;
+			Some((node.0, prev.1))
+		} else {
+			self.first = node.2;
+			None
 		};
 
-		let next = match index(node.2) {
-			Some(j) => {
-				let next = &mut self.regions[j];
-				next.0 = node.0;
-				Some(next.1)
-			},
-			None => None
+		let next = if node.2 != std::usize::MAX {
+			let next = &mut self.regions[node.2];
+			next.0 = node.0;
+			Some(next.1)
+		} else {
+			None
 		};
 
-		(prev, next)
+		(node.1, prev, next)
 	}
 
-	/// Free region starting at the given offset index.
-	fn free(&mut self, page_len: T, i: usize) -> Range<T> where T: Address {
-		let (prev, next) = self.remove(i);
+	/// Index of the allocated region starting exactly at `offset`, if any.
+	///
+	/// Linear in the number of allocated regions: there is no offset-keyed
+	/// index over allocated regions (unlike free regions), since an
+	/// `Allocation` already carries its own offset back to the caller.
+	fn find(&self, offset: T) -> Option<usize> where T: Address {
+		let mut cursor = self.first;
+		while cursor != std::usize::MAX {
+			let (_, region, next) = self.regions[cursor];
+			if region.offset == offset {
+				return Some(cursor);
+			}
+			cursor = next;
+		}
+		None
+	}
 
-		let free_region_start = match prev {
-			Some(prev) => prev.offset + prev.len,
-			None => T::ZERO
+	/// Index of the allocated region on `page` whose span contains `offset`.
+	fn find_covering(&self, page: usize, offset: T) -> Option<usize> where T: Address {
+		let mut cursor = self.first;
+		while cursor != std::usize::MAX {
+			let (_, region, next) = self.regions[cursor];
+			if region.page == page && region.offset <= offset && offset < region.offset + region.len {
+				return Some(cursor);
+			}
+			cursor = next;
+		}
+		None
+	}
+
+	/// Frees the allocated region at index `i`.
+	///
+	/// Returns the freed region, the index of the allocated region
+	/// immediately before it (if any), and the full free span now
+	/// available between its neighbours (clamped to `page_len`).
+	fn free(&mut self, page_len: T, i: usize) -> (AllocatedRegion<T>, Option<usize>, Range<T>) where T: Address {
+		let (region, prev, next) = self.remove(i);
+
+		let (prev_index, free_region_start) = match prev {
+			Some((j, prev)) => (Some(j), prev.offset + prev.len),
+			None => (None, T::ZERO)
 		};
 
 		let free_region_end = match next {
@@ -180,19 +374,21 @@ impl<T> AllocatedRegions<T> {
 			None => page_len
 		};
 
-		free_region_start..free_region_end
+		(region, prev_index, free_region_start..free_region_end)
 	}
 
 	/// Allocate a new free region.
 	///
-	/// Returns the range of the next free region.
-	fn allocate(&mut self, page_len: T, i: Option<usize>, offset: T, len: T) -> Range<T> where T: Address {
+	/// Returns the index of the newly allocated region, and the range of
+	/// the next free region.
+	fn allocate(&mut self, page: usize, page_len: T, i: Option<usize>, offset: T, len: T) -> (usize, Range<T>) where T: Address {
 		let region = AllocatedRegion {
+			page,
 			offset,
 			len
 		};
 
-		let next_region = match i {
+		let (n, next_region) = match i {
 			Some(i) => self.insert_after(i, region),
 			None => self.push_front(region)
 		};
@@ -203,7 +399,7 @@ impl<T> AllocatedRegions<T> {
 			None => page_len
 		};
 
-		next_free_region_start..next_free_region_end
+		(n, next_free_region_start..next_free_region_end)
 	}
 }
 
@@ -219,6 +415,15 @@ pub struct Allocation<T> {
 	pub len: T
 }
 
+/// Note: this does *not* take a custom allocator. That was tried
+/// (threading an `A: Allocator + Copy` parameter through to `pages`,
+/// `allocated_regions`, `free_regions` and the backing `BTreeMap`s) and
+/// reverted, because neither `slab::Slab` nor `btree_slab::BTreeMap` expose
+/// an allocator-aware constructor upstream to build those fields with —
+/// there was nowhere for the parameter to actually take effect. Revisit
+/// once one of those crates grows that support; until then, `FreeMap`'s own
+/// bookkeeping always lives on the global allocator, independently of
+/// whatever address space it manages.
 pub struct FreeMap<T: Address> {
 	/// Prefered allocation strategy.
 	strategy: AllocationStrategy,
@@ -233,7 +438,20 @@ pub struct FreeMap<T: Address> {
 	free_regions: FreeRegions<T>,
 
 	/// Maps a size to a free region of the given size.
-	map: BTreeMap<T, usize>
+	///
+	/// Used to answer `BestFit`/`WorstFit` queries.
+	map: BTreeMap<T, usize>,
+
+	/// Maps the offset of a free region to its index in `free_regions`.
+	///
+	/// Used to answer `FirstFit` queries: the lowest-address free region
+	/// that fits, found in address order via `btree::BTreeFreeMapByOffset`.
+	offset_map: BTreeMap<T, usize>,
+
+	/// Size-class free lists.
+	///
+	/// Used to answer `SegregatedFit` queries in O(1).
+	segregated_free_lists: SegregatedFreeLists
 }
 
 impl<T: Address> FreeMap<T> {
@@ -244,10 +462,146 @@ impl<T: Address> FreeMap<T> {
 			pages: Slab::new(),
 			allocated_regions: AllocatedRegions::new(),
 			free_regions: FreeRegions::new(),
-			map: BTreeMap::new()
+			map: BTreeMap::new(),
+			offset_map: BTreeMap::new(),
+			segregated_free_lists: SegregatedFreeLists::new()
+		}
+	}
+
+	/// Builds a `FreeMap` with a single page of length `page_len` from free
+	/// regions already sorted by offset, in O(n log n).
+	///
+	/// This is the fast path for snapshot restore and for initializing a
+	/// large address space whose occupancy layout is known up front: it
+	/// walks `free` in a single linear pass, appending straight onto the
+	/// tail of `allocated_regions`/`free_regions` in O(1) per region instead
+	/// of searching for an insertion point as `allocate` would. Each region
+	/// still costs an O(log n) insert into the size-/offset-keyed
+	/// `BTreeMap`s (via `register_free_region`), since `btree_slab` exposes
+	/// no bulk-load-from-sorted-runs primitive to build those in O(n)
+	/// instead; that leaves this O(n log n) overall rather than O(n), but
+	/// still well ahead of the O(n log n) (with a larger constant) of `n`
+	/// individual `allocate` round-trips. `free` must yield non-overlapping
+	/// ranges within `T::ZERO..page_len` in increasing order of `start`;
+	/// everything not covered by them is treated as a single allocated
+	/// region filling the gap.
+	#[inline]
+	pub fn from_sorted_free_regions<I>(strategy: AllocationStrategy, page_len: T, free: I) -> FreeMap<T>
+		where I: IntoIterator<Item = Range<T>>
+	{
+		let mut map = Self::new(strategy);
+		let page = map.new_page(page_len);
+		map.populate_page(page, page_len, free);
+		map
+	}
+
+	/// Populates a freshly created, still-empty `page` (spanning
+	/// `T::ZERO..page_len`) from free regions already sorted by offset, in
+	/// O(n log n) (one pass, with an O(log n) `register_free_region` per
+	/// free region); the core shared by `from_sorted_free_regions` and
+	/// `split_off`.
+	fn populate_page<I>(&mut self, page: usize, page_len: T, free: I)
+		where I: IntoIterator<Item = Range<T>>
+	{
+		let mut cursor = T::ZERO;
+		let mut previous_allocated_region: Option<usize> = None;
+
+		for region in free {
+			if region.start > cursor {
+				let gap = AllocatedRegion {
+					page,
+					offset: cursor,
+					len: region.start - cursor
+				};
+				previous_allocated_region = Some(self.allocated_regions.append(previous_allocated_region, gap));
+			}
+
+			let free_region = FreeRegion {
+				page,
+				offset: region.start,
+				len: region.len(),
+				previous_allocated_region: previous_allocated_region.unwrap_or(std::usize::MAX)
+			};
+			self.register_free_region(free_region);
+
+			cursor = region.end;
+		}
+
+		if cursor < page_len {
+			let gap = AllocatedRegion {
+				page,
+				offset: cursor,
+				len: page_len - cursor
+			};
+			self.allocated_regions.append(previous_allocated_region, gap);
 		}
 	}
 
+	/// Splits the managed space within `page` at `at`, returning a new
+	/// `FreeMap` that owns everything at or beyond `at` while `self` keeps
+	/// the lower portion. Any free or allocated region straddling `at` is
+	/// split into two.
+	///
+	/// Like `from_sorted_free_regions`, this can't lean on a `btree_slab`
+	/// split primitive (none is exposed upstream), so instead of splitting
+	/// the underlying trees/slabs node-by-node it removes all of `page`'s
+	/// free and allocated regions and rebuilds both halves from `page`'s
+	/// free regions alone. Each half's allocated regions come back as the
+	/// gaps between its free regions: a region straddling `at` is split in
+	/// two, but adjacent allocated regions on the same side of `at` come
+	/// back as a single allocated region, so the boundaries between them
+	/// are not kept.
+	///
+	/// The returned `FreeMap` manages a fresh page 0 spanning
+	/// `T::ZERO..(page_len - at)`; its offsets are `self`'s offsets at or
+	/// beyond `at`, shifted down by `at`.
+	pub fn split_off(&mut self, page: usize, at: T) -> FreeMap<T> {
+		let page_len = self.pages[page].len;
+
+		let mut free_ranges: Vec<Range<T>> = self.free_regions.regions.iter()
+			.filter(|(_, (region, _))| region.page == page)
+			.map(|(_, (region, _))| region.offset..(region.offset + region.len))
+			.collect();
+
+		let mut allocated_indices = Vec::new();
+		let mut cursor = self.allocated_regions.first;
+		while cursor != std::usize::MAX {
+			let (_, region, next) = self.allocated_regions.regions[cursor];
+			if region.page == page {
+				allocated_indices.push(cursor);
+			}
+			cursor = next;
+		}
+		for i in allocated_indices {
+			self.allocated_regions.remove(i);
+		}
+
+		let free_offsets: Vec<T> = free_ranges.iter().map(|r| r.start).collect();
+		for offset in free_offsets {
+			self.unregister_free_region(offset);
+		}
+
+		free_ranges.sort_by_key(|r| r.start);
+
+		let mut lower_free = Vec::new();
+		let mut upper_free = Vec::new();
+		for r in free_ranges {
+			if r.end <= at {
+				lower_free.push(r);
+			} else if r.start >= at {
+				upper_free.push((r.start - at)..(r.end - at));
+			} else {
+				lower_free.push(r.start..at);
+				upper_free.push(T::ZERO..(r.end - at));
+			}
+		}
+
+		self.pages[page].len = at;
+		self.populate_page(page, at, lower_free);
+
+		Self::from_sorted_free_regions(self.strategy, page_len - at, upper_free)
+	}
+
 	/// Add a new empty page.
 	///
 	/// Returns the index used to uniquely identify the page.
@@ -260,16 +614,64 @@ impl<T: Address> FreeMap<T> {
 	#[inline]
 	pub fn allocate(&mut self, len: T) -> Result<Allocation<T>, AllocationFailed> {
 		if len > T::ZERO {
-			match self.map.address_of_free_range(len, self.strategy) {
-				Some((addr, region_len)) => {
+			if self.strategy == AllocationStrategy::SegregatedFit {
+				return self.allocate_segregated(len);
+			}
+
+			// `FirstFit` is address-ordered, so it is resolved through the
+			// offset-keyed tree instead of the size-keyed one used by
+			// `BestFit`/`WorstFit`.
+			let found = match self.strategy {
+				AllocationStrategy::FirstFit => {
+					let free_regions = &self.free_regions;
+					self.offset_map.address_of_first_fit(len, &|i| free_regions.region_len(i))
+				},
+				AllocationStrategy::WorstFit | AllocationStrategy::BestFit => {
+					self.map.address_of_free_range(len, self.strategy).map(|(addr, _)| addr)
+				},
+				AllocationStrategy::SegregatedFit => unreachable!()
+			};
+
+			match found {
+				Some(addr) => {
 					let free_regions = &mut self.free_regions;
-					let free_region = self.map.update_at(addr, move |i| free_regions.pop(i));
+					let free_region = match self.strategy {
+						// `addr` is already an address into `offset_map` here,
+						// so popping through it removes the consumed entry
+						// directly.
+						AllocationStrategy::FirstFit => self.offset_map.update_at(addr, move |i| free_regions.pop(i)),
+						// `addr` is an address into the size-keyed `map`
+						// instead, so the consumed region's `offset_map` entry
+						// has to be removed separately below.
+						AllocationStrategy::WorstFit | AllocationStrategy::BestFit => self.map.update_at(addr, move |i| free_regions.pop(i)),
+						AllocationStrategy::SegregatedFit => unreachable!()
+					};
+					if self.strategy != AllocationStrategy::FirstFit {
+						self.offset_map.remove(&free_region.offset).expect("offset_map out of sync with free_regions");
+					}
 
 					let page_len = self.pages[free_region.page].len;
-					let new_free_region_range = self.allocated_regions.allocate(page_len, index(free_region.previous_allocated_region), free_region.offset, len);
+					let previous_allocated_region = if free_region.previous_allocated_region != std::usize::MAX {
+						Some(free_region.previous_allocated_region)
+					} else {
+						None
+					};
+					let (new_region_index, new_free_region_range) = self.allocated_regions.allocate(free_region.page, page_len, previous_allocated_region, free_region.offset, len);
 
-					let free_regions = &mut self.free_regions;
-					self.map.update(len, move |i| free_regions.push(i)); // O(log n)
+					// The leftover tail of the old free region (if any) now
+					// sits right after the region we just allocated, so it
+					// must be re-registered under its own length, not the
+					// requested `len`, and chained from the newly allocated
+					// region rather than the old (now stale) predecessor.
+					let new_free_region_len = new_free_region_range.len();
+					if new_free_region_len > T::ZERO {
+						self.register_free_region(FreeRegion {
+							page: free_region.page,
+							offset: new_free_region_range.start,
+							len: new_free_region_len,
+							previous_allocated_region: new_region_index
+						});
+					}
 
 					Ok(Allocation {
 						page: free_region.page,
@@ -288,8 +690,344 @@ impl<T: Address> FreeMap<T> {
 		}
 	}
 
+	/// `SegregatedFit` allocation: O(1) pop from the size-class free lists.
+	fn allocate_segregated(&mut self, len: T) -> Result<Allocation<T>, AllocationFailed> {
+		let class = SegregatedFreeLists::class_of(len);
+
+		match self.segregated_free_lists.pop(&self.free_regions, class, len) {
+			Some(i) => {
+				// Already unlinked from its class chain by `pop` above; take
+				// it out of the slab entirely, and out of the offset-keyed
+				// tree it's indexed in regardless of strategy.
+				let free_region = self.free_regions.take(i);
+				self.offset_map.remove(&free_region.offset).expect("offset_map out of sync with free_regions");
+
+				let page_len = self.pages[free_region.page].len;
+				let previous_allocated_region = if free_region.previous_allocated_region != std::usize::MAX {
+					Some(free_region.previous_allocated_region)
+				} else {
+					None
+				};
+				let (new_region_index, new_free_region_range) = self.allocated_regions.allocate(free_region.page, page_len, previous_allocated_region, free_region.offset, len);
+
+				// As in `allocate`: the leftover tail is chained from the
+				// region we just allocated, not from the old predecessor.
+				let new_free_region_len = new_free_region_range.len();
+				if new_free_region_len > T::ZERO {
+					self.register_free_region(FreeRegion {
+						page: free_region.page,
+						offset: new_free_region_range.start,
+						len: new_free_region_len,
+						previous_allocated_region: new_region_index
+					});
+				}
+
+				Ok(Allocation {
+					page: free_region.page,
+					offset: free_region.offset,
+					len
+				})
+			},
+			None => Err(AllocationFailed)
+		}
+	}
+
+	/// Frees a previously allocated region, merging it with any adjacent
+	/// free region(s) (boundary-tag coalescing).
+	///
+	/// `offset` and `len` must match a region previously handed out by
+	/// `allocate` exactly; use `free_range` to free part of one instead.
 	#[inline]
-	pub fn free(&mut self, offset: T, len: T) {
-		panic!("TODO")
+	pub fn free(&mut self, offset: T, _len: T) {
+		let i = self.allocated_regions.find(offset).expect("no allocated region at the given offset");
+		self.free_allocated(i);
+	}
+
+	/// Frees the sub-range `[offset, offset + len)` of `page`, splitting
+	/// the allocated region that covers it if the freed span doesn't cover
+	/// it exactly, and coalescing with adjacent free regions.
+	pub fn free_range(&mut self, page: usize, offset: T, len: T) {
+		let i = self.allocated_regions.find_covering(page, offset).expect("no allocated region covers the given range");
+		let page_len = self.pages[page].len;
+
+		let (removed, prev_index, gap) = self.allocated_regions.free(page_len, i);
+		let freed_end = offset + len;
+
+		let mut last_index = prev_index;
+		let mut free_start = removed.offset;
+		let mut free_end = removed.offset + removed.len;
+
+		if removed.offset < offset {
+			// Left part of the covering region stays allocated.
+			let left = AllocatedRegion { page, offset: removed.offset, len: offset - removed.offset };
+			let (n, _) = match last_index {
+				Some(j) => self.allocated_regions.insert_after(j, left),
+				None => self.allocated_regions.push_front(left)
+			};
+			last_index = Some(n);
+			free_start = offset;
+		} else if gap.start < removed.offset {
+			self.unregister_free_region(gap.start);
+			free_start = gap.start;
+		}
+
+		if freed_end < removed.offset + removed.len {
+			// Right part of the covering region stays allocated.
+			let right = AllocatedRegion { page, offset: freed_end, len: (removed.offset + removed.len) - freed_end };
+			let (right_index, _) = match last_index {
+				Some(j) => self.allocated_regions.insert_after(j, right),
+				None => self.allocated_regions.push_front(right)
+			};
+			free_end = freed_end;
+			// A free region right after the covering one now follows `right`.
+			if gap.end > removed.offset + removed.len {
+				if let Some(&j) = self.offset_map.get(&(removed.offset + removed.len)) {
+					self.free_regions.regions[j].0.previous_allocated_region = right_index;
+				}
+			}
+		} else if gap.end > removed.offset + removed.len {
+			self.unregister_free_region(removed.offset + removed.len);
+			free_end = gap.end;
+		}
+
+		self.register_free_region(FreeRegion {
+			page,
+			offset: free_start,
+			len: free_end - free_start,
+			previous_allocated_region: last_index.unwrap_or(std::usize::MAX)
+		});
+	}
+
+	/// Shared by `free`/`free_range` for the common case of freeing an
+	/// allocated region in its entirety: removes it and registers the
+	/// resulting (possibly coalesced) free span.
+	fn free_allocated(&mut self, i: usize) {
+		let page = self.allocated_regions.regions[i].1.page;
+		let page_len = self.pages[page].len;
+
+		let (removed, prev_index, gap) = self.allocated_regions.free(page_len, i);
+
+		if gap.start < removed.offset {
+			self.unregister_free_region(gap.start);
+		}
+		if gap.end > removed.offset + removed.len {
+			self.unregister_free_region(removed.offset + removed.len);
+		}
+
+		self.register_free_region(FreeRegion {
+			page: removed.page,
+			offset: gap.start,
+			len: gap.end - gap.start,
+			previous_allocated_region: prev_index.unwrap_or(std::usize::MAX)
+		});
+	}
+
+	/// Inserts a new free region and indexes it in the offset-keyed tree
+	/// and in whichever strategy-specific structure applies, so both stay
+	/// consistent with each other.
+	fn register_free_region(&mut self, region: FreeRegion<T>) {
+		let offset = region.offset;
+		let len = region.len;
+		let i = self.free_regions.insert(region);
+
+		self.offset_map.insert(offset, i);
+
+		match self.strategy {
+			AllocationStrategy::FirstFit => {},
+			AllocationStrategy::WorstFit | AllocationStrategy::BestFit => {
+				let free_regions = &mut self.free_regions;
+				self.map.update(len, move |head: Option<usize>| (free_regions.push(head, i), ()));
+			},
+			AllocationStrategy::SegregatedFit => {
+				let class = SegregatedFreeLists::class_of(len);
+				self.segregated_free_lists.push(&mut self.free_regions, class, i);
+			}
+		}
+	}
+
+	/// Removes the free region starting at `offset` from `free_regions` and
+	/// from every index structure it is registered in.
+	fn unregister_free_region(&mut self, offset: T) -> FreeRegion<T> {
+		let i = self.offset_map.remove(&offset).expect("no free region at the given offset");
+		let len = self.free_regions.region_len(i);
+
+		match self.strategy {
+			AllocationStrategy::FirstFit => {},
+			AllocationStrategy::WorstFit | AllocationStrategy::BestFit => {
+				let free_regions = &mut self.free_regions;
+				self.map.update(len, move |head: Option<usize>| (unlink(free_regions, head, i), ()));
+			},
+			AllocationStrategy::SegregatedFit => {
+				let class = SegregatedFreeLists::class_of(len);
+				self.unlink_from_segregated(class, i);
+			}
+		}
+
+		self.free_regions.take(i)
+	}
+
+	/// Unlinks slab index `target` from class `class`'s chain in
+	/// `segregated_free_lists`.
+	fn unlink_from_segregated(&mut self, class: usize, target: usize) {
+		if class >= self.segregated_free_lists.classes.len() {
+			return;
+		}
+
+		let head = self.segregated_free_lists.classes[class];
+		if head == target {
+			self.segregated_free_lists.classes[class] = self.free_regions.regions[target].1;
+			return;
+		}
+
+		let mut prev = head;
+		while prev != std::usize::MAX {
+			let next = self.free_regions.regions[prev].1;
+			if next == target {
+				self.free_regions.regions[prev].1 = self.free_regions.regions[next].1;
+				return;
+			}
+			prev = next;
+		}
+	}
+}
+
+/// Unlinks slab index `target` from the same-size chain starting at `head`
+/// (threaded through `FreeRegions`' own "next" slot), returning the new
+/// chain head.
+fn unlink<T>(free_regions: &mut FreeRegions<T>, head: Option<usize>, target: usize) -> Option<usize> {
+	let head = head?;
+	if head == target {
+		let next = free_regions.regions[head].1;
+		return if next != std::usize::MAX { Some(next) } else { None };
+	}
+
+	let mut prev = head;
+	loop {
+		let next = free_regions.regions[prev].1;
+		if next == std::usize::MAX {
+			// `target` wasn't actually in this chain; leave it untouched.
+			return Some(head);
+		}
+		if next == target {
+			free_regions.regions[prev].1 = free_regions.regions[next].1;
+			return Some(head);
+		}
+		prev = next;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	impl Address for u64 {
+		const ZERO: u64 = 0;
+		const BIT_WIDTH: u32 = u64::BITS;
+
+		fn leading_zeros(self) -> u32 {
+			u64::leading_zeros(self)
+		}
+
+		fn shr(self, shift: u32) -> Self {
+			self >> shift
+		}
+	}
+
+	/// Allocated-region spans on `page`, as `(offset, len)`, sorted by offset.
+	fn allocated_spans(map: &FreeMap<u64>, page: usize) -> Vec<(u64, u64)> {
+		let mut spans = Vec::new();
+		let mut cursor = map.allocated_regions.first;
+		while cursor != std::usize::MAX {
+			let (_, region, next) = map.allocated_regions.regions[cursor];
+			if region.page == page {
+				spans.push((region.offset, region.len));
+			}
+			cursor = next;
+		}
+		spans.sort();
+		spans
+	}
+
+	/// Free-region spans on `page`, as `(offset, len)`; each entry is also
+	/// checked against `offset_map` to make sure the two stay in sync.
+	fn free_spans(map: &FreeMap<u64>, page: usize) -> Vec<(u64, u64)> {
+		let mut spans = Vec::new();
+		for (i, (region, _)) in map.free_regions.regions.iter() {
+			if region.page == page {
+				assert_eq!(
+					map.offset_map.get(&region.offset), Some(&i),
+					"offset_map out of sync with free_regions at offset {:?}", region.offset
+				);
+				spans.push((region.offset, region.len));
+			}
+		}
+		spans.sort();
+		spans
+	}
+
+	#[test]
+	fn free_with_no_free_neighbor() {
+		let mut map = FreeMap::from_sorted_free_regions(AllocationStrategy::FirstFit, 100u64, vec![0..100]);
+		map.allocate(10).unwrap(); // A: 0..10
+		map.allocate(10).unwrap(); // B: 10..20
+		map.allocate(10).unwrap(); // C: 20..30
+		// tail 30..100 stays free
+
+		map.free(10, 10); // free B: both neighbours (A, C) are still allocated
+		assert_eq!(free_spans(&map, 0), vec![(10, 10), (30, 70)]);
+	}
+
+	#[test]
+	fn free_merges_with_right_then_both() {
+		let mut map = FreeMap::from_sorted_free_regions(AllocationStrategy::FirstFit, 100u64, vec![0..100]);
+		map.allocate(10).unwrap(); // A: 0..10
+		map.allocate(10).unwrap(); // B: 10..20
+		map.allocate(10).unwrap(); // C: 20..30
+		map.allocate(10).unwrap(); // D: 30..40
+		// tail 40..100 stays free
+
+		map.free(10, 10); // free B: isolated, no free neighbour yet
+		assert_eq!(free_spans(&map, 0), vec![(10, 10), (40, 60)]);
+
+		map.free(0, 10); // free A: merges with the now-free B on its right
+		assert_eq!(free_spans(&map, 0), vec![(0, 20), (40, 60)]);
+
+		map.free(30, 10); // free D: merges with the free tail on its right
+		assert_eq!(free_spans(&map, 0), vec![(0, 20), (30, 70)]);
+
+		map.free(20, 10); // free C: both neighbours (0..20 and 30..100) are free
+		assert_eq!(free_spans(&map, 0), vec![(0, 100)]);
+		assert_eq!(allocated_spans(&map, 0), Vec::new());
+
+		// The whole page coalesced into one free region: a full-page
+		// allocation must now succeed.
+		let allocation = map.allocate(100).unwrap();
+		assert_eq!((allocation.offset, allocation.len), (0, 100));
+	}
+
+	#[test]
+	fn split_off_splits_a_straddling_allocated_region() {
+		let mut map = FreeMap::from_sorted_free_regions(AllocationStrategy::FirstFit, 100u64, vec![0..100]);
+		map.allocate(40).unwrap(); // A: 0..40
+		map.allocate(20).unwrap(); // B: 40..60, straddles the split point at 50
+		// tail 60..100 stays free
+
+		let mut right = map.split_off(0, 50);
+
+		// `self` keeps 0..50: A whole, and the left half of B; since
+		// `split_off` rebuilds the page from its free regions alone (see
+		// its doc comment), the two end up as a single coalesced
+		// allocated span rather than preserving the original A/B boundary.
+		assert_eq!(allocated_spans(&map, 0), vec![(0, 50)]);
+		assert_eq!(free_spans(&map, 0), Vec::new());
+		assert!(map.allocate(1).is_err());
+
+		// `right` owns 50..100, shifted down to 0..50: the right half of B
+		// (now 0..10), and the free tail (now 10..50).
+		assert_eq!(allocated_spans(&right, 0), vec![(0, 10)]);
+		assert_eq!(free_spans(&right, 0), vec![(10, 40)]);
+
+		let allocation = right.allocate(40).unwrap();
+		assert_eq!((allocation.page, allocation.offset, allocation.len), (0, 10, 40));
 	}
 }